@@ -1,4 +1,5 @@
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 use std::time::Duration;
 
 use chrono::{DateTime, SecondsFormat, Utc};
@@ -29,6 +30,18 @@ pub struct RequestTelemetry {
     // Results of a request execution. HTTP status code for HTTP requests.
     response_code: StatusCode,
 
+    // Source of the request. Examples are the instrumentation key of the caller, or the ip
+    // address of the caller.
+    source: Option<String>,
+
+    // Explicit override of the success classification, set through `set_success`. Takes
+    // precedence over both `classifier` and the default rule.
+    success_override: Option<bool>,
+
+    // Pluggable success classifier set through `set_success_classifier`, consulted when no
+    // explicit override is present.
+    classifier: Option<SuccessClassifier>,
+
     /// The time stamp when this telemetry was measured.
     timestamp: DateTime<Utc>,
 
@@ -66,6 +79,9 @@ impl RequestTelemetry {
             uri,
             duration: FormattedDuration(duration),
             response_code: response_code.into(),
+            source: None,
+            success_override: None,
+            classifier: None,
             timestamp: time::now(),
             properties: Default::default(),
             tags: Default::default(),
@@ -83,10 +99,113 @@ impl RequestTelemetry {
         &mut self.measurements
     }
 
-    // Returns an indication of successful or unsuccessful call.
+    /// Returns mutable reference to the request name, so callers that only learn the route
+    /// template (e.g. `GET /users/{id}`) after construction can replace the raw path with it.
+    pub fn name_mut(&mut self) -> &mut String {
+        &mut self.name
+    }
+
+    // Returns an indication of successful or unsuccessful call, using the default rule (anything
+    // below 400, plus 401, is successful) unless this item overrides it. Prefer
+    // `resolve_success` when a `TelemetryContext`-level classifier is in scope, e.g. from the
+    // `From` conversion into an `Envelope`.
     pub fn is_success(&self) -> bool {
+        self.resolve_success(None)
+    }
+
+    /// Returns an indication of successful or unsuccessful call. Defers to the explicit override
+    /// set through [`RequestTelemetry::set_success`], then to the classifier set through
+    /// [`RequestTelemetry::set_success_classifier`], then to `context_classifier` (the
+    /// `TelemetryContext`-wide default, if one is configured), and finally falls back to the
+    /// default rule: anything below 400, plus 401, is successful.
+    pub(crate) fn resolve_success(&self, context_classifier: Option<&SuccessClassifier>) -> bool {
+        if let Some(success) = self.success_override {
+            return success;
+        }
+
+        if let Some(classify) = &self.classifier {
+            return classify(self.response_code);
+        }
+
+        if let Some(classify) = context_classifier {
+            return classify(self.response_code);
+        }
+
         self.response_code < StatusCode::BAD_REQUEST || self.response_code == StatusCode::UNAUTHORIZED
     }
+
+    /// Overrides the success classification for this request, taking precedence over the
+    /// classifier set through [`RequestTelemetry::set_success_classifier`], any
+    /// `TelemetryContext`-wide classifier, and the default rule. Useful for endpoints where a
+    /// single request doesn't fit the general classification, e.g. a cache-probe endpoint that
+    /// treats `404` as success.
+    pub fn set_success(&mut self, success: bool) {
+        self.success_override = Some(success);
+    }
+
+    /// Sets a classifier that decides success from the `StatusCode`, replacing both the
+    /// `TelemetryContext`-wide classifier and the default rule for this request, unless
+    /// overridden by [`RequestTelemetry::set_success`]. Useful for one-off requests that don't
+    /// fit the rest of the application, e.g. a gRPC-over-HTTP endpoint with its own status
+    /// mapping.
+    pub fn set_success_classifier(&mut self, classify: impl Fn(StatusCode) -> bool + Send + Sync + 'static) {
+        self.classifier = Some(Arc::new(classify));
+    }
+
+    /// Like [`RequestTelemetry::set_success_classifier`], but takes an already-shared classifier
+    /// directly, so callers holding one `Arc` across many requests (e.g. `TelemetryMiddleware`)
+    /// don't pay for re-wrapping it on every item.
+    pub(crate) fn set_shared_success_classifier(&mut self, classifier: SuccessClassifier) {
+        self.classifier = Some(classifier);
+    }
+
+    /// Correlates this request with an incoming distributed trace described by a W3C
+    /// `traceparent` header (`00-<32 hex trace-id>-<16 hex parent-id>-<2 hex flags>`).
+    ///
+    /// The trace-id is mapped into the `ai.operation.id` context tag and the incoming parent
+    /// span id into `ai.operation.parentId`, while this telemetry item's own [`RequestTelemetry`]
+    /// id is submitted as its span id, so that dependency calls recorded underneath it chain onto
+    /// the same operation.
+    ///
+    /// Returns a [`TraceParentError`] if `traceparent` isn't a well-formed version 00 header.
+    pub fn with_correlation(mut self, traceparent: &str) -> Result<Self, TraceParentError> {
+        let trace_parent = TraceParent::parse(traceparent)?;
+
+        self.tags
+            .insert("ai.operation.id".into(), trace_parent.trace_id);
+        self.tags
+            .insert("ai.operation.parentId".into(), trace_parent.parent_id);
+
+        Ok(self)
+    }
+
+    /// Sets the application id of the caller that issued this request, submitted as
+    /// `RequestData::source`.
+    pub fn set_source(&mut self, source: impl Into<String>) {
+        self.source = Some(source.into());
+    }
+
+    /// Returns the request name, for components that bucket requests by name (e.g. a metrics
+    /// aggregator) without converting the whole item into an `Envelope`.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the raw duration to serve the request.
+    pub(crate) fn raw_duration(&self) -> Duration {
+        self.duration.0
+    }
+
+    /// Returns the HTTP status code the request completed with.
+    pub(crate) fn response_code(&self) -> StatusCode {
+        self.response_code
+    }
+
+    /// Returns the dotnet duration-aware formatting of a `Duration`, reusing the same rules
+    /// `RequestTelemetry::duration` is submitted with.
+    pub(crate) fn format_duration(duration: Duration) -> String {
+        FormattedDuration(duration).to_string()
+    }
 }
 
 impl Telemetry for RequestTelemetry {
@@ -118,20 +237,22 @@ impl Telemetry for RequestTelemetry {
 
 impl From<(TelemetryContext, RequestTelemetry)> for Envelope {
     fn from((context, telemetry): (TelemetryContext, RequestTelemetry)) -> Self {
-        let success = telemetry.is_success();
-        let data = Data::RequestData(
-            RequestDataBuilder::new(
-                telemetry.id.to_hyphenated().to_string(),
-                telemetry.duration.to_string(),
-                telemetry.response_code.as_str(),
-            )
+        let success = telemetry.resolve_success(context.success_classifier.as_ref());
+        let mut builder = RequestDataBuilder::new(
+            telemetry.id.to_hyphenated().to_string(),
+            telemetry.duration.to_string(),
+            telemetry.response_code.as_str(),
+        );
+        builder = builder
             .name(telemetry.name)
             .success(success)
             .url(telemetry.uri.to_string())
             .properties(Properties::combine(context.properties, telemetry.properties))
-            .measurements(telemetry.measurements)
-            .build(),
-        );
+            .measurements(telemetry.measurements);
+        if let Some(source) = telemetry.source {
+            builder = builder.source(source);
+        }
+        let data = Data::RequestData(builder.build());
 
         let envelope_name = data.envelope_name(&context.normalized_i_key);
         let timestamp = telemetry.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true);
@@ -144,6 +265,19 @@ impl From<(TelemetryContext, RequestTelemetry)> for Envelope {
     }
 }
 
+/// A pluggable success classifier, set through [`RequestTelemetry::set_success_classifier`] or,
+/// for a process-wide default, through `TelemetryContext::set_success_classifier`.
+///
+/// Stored behind an `Arc` so a single classifier built once for the application can be cloned
+/// cheaply onto every `RequestTelemetry` it creates, rather than rebuilt per request. Configuring
+/// it on the `TelemetryContext` is the recommended way to set it once for an entire application;
+/// [`RequestTelemetry::set_success_classifier`] and [`RequestTelemetry::set_success`] only exist
+/// to override that default for requests that don't fit it. The `actix` feature's
+/// `TelemetryMiddleware` forwards its own classifier this way too, via
+/// [`RequestTelemetry::set_shared_success_classifier`], so an actix-web service configures it
+/// once instead of per item.
+pub type SuccessClassifier = Arc<dyn Fn(StatusCode) -> bool + Send + Sync>;
+
 /// Provides dotnet duration aware formatting rules.
 struct FormattedDuration(Duration);
 
@@ -165,6 +299,73 @@ impl Display for FormattedDuration {
     }
 }
 
+/// A parsed [W3C Trace Context](https://www.w3.org/TR/trace-context/) `traceparent` header.
+struct TraceParent {
+    trace_id: String,
+    parent_id: String,
+}
+
+impl TraceParent {
+    /// Parses a `traceparent` header of the form
+    /// `00-<32 hex trace-id>-<16 hex parent-id>-<2 hex flags>`, rejecting anything but a
+    /// well-formed version 00 header with non-zero trace-id and parent-id.
+    fn parse(value: &str) -> Result<Self, TraceParentError> {
+        let (version, trace_id, parent_id, flags) = match value.split('-').collect::<Vec<_>>()[..] {
+            [version, trace_id, parent_id, flags] => (version, trace_id, parent_id, flags),
+            _ => return Err(TraceParentError::Malformed),
+        };
+
+        if version != "00" {
+            return Err(TraceParentError::UnsupportedVersion);
+        }
+        if flags.len() != 2 || !is_lowercase_hex(flags) {
+            return Err(TraceParentError::Malformed);
+        }
+        if trace_id.len() != 32 || !is_lowercase_hex(trace_id) || trace_id.bytes().all(|b| b == b'0') {
+            return Err(TraceParentError::InvalidTraceId);
+        }
+        if parent_id.len() != 16 || !is_lowercase_hex(parent_id) || parent_id.bytes().all(|b| b == b'0') {
+            return Err(TraceParentError::InvalidParentId);
+        }
+
+        Ok(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+        })
+    }
+}
+
+fn is_lowercase_hex(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Reasons a `traceparent` header was rejected by [`RequestTelemetry::with_correlation`].
+#[derive(Debug, Eq, PartialEq)]
+pub enum TraceParentError {
+    /// The header doesn't have the `<version>-<trace-id>-<parent-id>-<flags>` shape expected.
+    Malformed,
+    /// The header's version byte isn't the only one this crate understands, `00`.
+    UnsupportedVersion,
+    /// The trace-id isn't 32 lowercase hex digits, or is all zeros.
+    InvalidTraceId,
+    /// The parent-id isn't 16 lowercase hex digits, or is all zeros.
+    InvalidParentId,
+}
+
+impl Display for TraceParentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            TraceParentError::Malformed => "traceparent header is malformed",
+            TraceParentError::UnsupportedVersion => "traceparent header has an unsupported version",
+            TraceParentError::InvalidTraceId => "traceparent header has an invalid trace-id",
+            TraceParentError::InvalidParentId => "traceparent header has an invalid parent-id",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for TraceParentError {}
+
 #[cfg(not(test))]
 mod id {
     use uuid::Uuid;
@@ -318,4 +519,102 @@ mod tests {
     fn it_converts_duration_to_string(duration: Duration, expected: &'static str) {
         assert_eq!(FormattedDuration(duration).to_string(), expected.to_string())
     }
+
+    #[test]
+    fn it_correlates_with_an_incoming_traceparent() {
+        let telemetry = RequestTelemetry::new(
+            Method::GET,
+            "https://example.com/main.html".parse().unwrap(),
+            Duration::from_secs(2),
+            StatusCode::OK,
+        )
+        .with_correlation("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+        .unwrap();
+
+        assert_eq!(
+            telemetry.tags.get("ai.operation.id"),
+            Some(&"4bf92f3577b34da6a3ce929d0e0e4736".to_string())
+        );
+        assert_eq!(
+            telemetry.tags.get("ai.operation.parentId"),
+            Some(&"00f067aa0ba902b7".to_string())
+        );
+    }
+
+    #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7"; "too few fields")]
+    #[test_case("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"; "wrong version")]
+    #[test_case("00-00000000000000000000000000000000-00f067aa0ba902b7-01"; "all zero trace id")]
+    #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01"; "all zero parent id")]
+    #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1"; "short flags")]
+    #[test_case("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"; "uppercase trace id")]
+    fn it_rejects_invalid_traceparent_headers(traceparent: &str) {
+        let telemetry = RequestTelemetry::new(
+            Method::GET,
+            "https://example.com/main.html".parse().unwrap(),
+            Duration::from_secs(2),
+            StatusCode::OK,
+        );
+
+        assert!(telemetry.with_correlation(traceparent).is_err());
+    }
+
+    #[test]
+    fn it_applies_a_custom_success_classifier() {
+        let mut telemetry = RequestTelemetry::new(
+            Method::GET,
+            "https://example.com/main.html".parse().unwrap(),
+            Duration::from_secs(2),
+            StatusCode::NOT_FOUND,
+        );
+        assert!(!telemetry.is_success());
+
+        telemetry.set_success_classifier(|status| status == StatusCode::NOT_FOUND);
+        assert!(telemetry.is_success());
+    }
+
+    #[test]
+    fn it_lets_an_explicit_override_win_over_the_classifier() {
+        let mut telemetry = RequestTelemetry::new(
+            Method::GET,
+            "https://example.com/main.html".parse().unwrap(),
+            Duration::from_secs(2),
+            StatusCode::OK,
+        );
+        telemetry.set_success_classifier(|_| true);
+
+        telemetry.set_success(false);
+
+        assert!(!telemetry.is_success());
+    }
+
+    #[test]
+    fn it_falls_back_to_the_context_classifier_when_the_item_has_none() {
+        let mut context = TelemetryContext::new("instrumentation".into());
+        context.success_classifier = Some(Arc::new(|status| status == StatusCode::NOT_FOUND));
+
+        let telemetry = RequestTelemetry::new(
+            Method::GET,
+            "https://example.com/main.html".parse().unwrap(),
+            Duration::from_secs(2),
+            StatusCode::NOT_FOUND,
+        );
+
+        assert!(telemetry.resolve_success(context.success_classifier.as_ref()));
+    }
+
+    #[test]
+    fn it_lets_the_item_classifier_win_over_the_context_classifier() {
+        let mut context = TelemetryContext::new("instrumentation".into());
+        context.success_classifier = Some(Arc::new(|_| true));
+
+        let mut telemetry = RequestTelemetry::new(
+            Method::GET,
+            "https://example.com/main.html".parse().unwrap(),
+            Duration::from_secs(2),
+            StatusCode::NOT_FOUND,
+        );
+        telemetry.set_success_classifier(|_| false);
+
+        assert!(!telemetry.resolve_success(context.success_classifier.as_ref()));
+    }
 }
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::SecondsFormat;
+
+use crate::context::TelemetryContext;
+use crate::contracts::*;
+use crate::telemetry::RequestTelemetry;
+use crate::time;
+
+// Rolls individual RequestTelemetry items up into Rate/Error/Duration metrics instead of shipping
+// one envelope per request, so high-traffic endpoints don't flood the ingestion endpoint while
+// latency and error-rate visibility is preserved.
+#[derive(Default)]
+pub struct RequestMetricsAggregator {
+    buckets: HashMap<BucketKey, Bucket>,
+}
+
+impl RequestMetricsAggregator {
+    /// Creates an empty aggregator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Folds a request into the `request_count`/`request_error_count`/`request_duration` bucket for its `name`
+    /// and `response_code`, instead of tracking it as an individual telemetry item. Success is
+    /// resolved against `context`'s classifier the same way the `RequestTelemetry`→`Envelope`
+    /// conversion does, so the RED metrics agree with the individual telemetry items they're
+    /// rolled up from.
+    pub fn track(&mut self, telemetry: &RequestTelemetry, context: &TelemetryContext) {
+        let key = BucketKey {
+            name: telemetry.name().to_string(),
+            response_code: telemetry.response_code().as_str().to_string(),
+        };
+        let is_error = !telemetry.resolve_success(context.success_classifier.as_ref());
+
+        self.buckets.entry(key).or_default().record(telemetry.raw_duration(), is_error);
+    }
+
+    /// Drains every bucket accumulated since the last call and turns each one into three
+    /// pre-aggregated `MetricData` envelopes: `request_count` (a plain counter — the Rate in
+    /// RED), `request_duration` (an aggregation: sum, count, min, max, stdDev over the duration
+    /// samples — the Duration), and `request_error_count` (a plain counter — the Error).
+    pub fn collect(&mut self, context: &TelemetryContext) -> Vec<Envelope> {
+        let timestamp = time::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        self.buckets
+            .drain()
+            .flat_map(|(key, bucket)| {
+                let count_metric = DataPointBuilder::new("request_count", bucket.count as f64)
+                    .kind(DataPointType::Measurement)
+                    .build();
+
+                // `Aggregation` datapoints carry `value` as the *sum* of samples, with `count`
+                // alongside it so the backend derives the mean — not a pre-divided average.
+                let duration_metric = DataPointBuilder::new("request_duration", bucket.sum)
+                    .kind(DataPointType::Aggregation)
+                    .count(bucket.count as i32)
+                    .min(bucket.min)
+                    .max(bucket.max)
+                    .std_dev(bucket.std_dev())
+                    .build();
+
+                let error_metric = DataPointBuilder::new("request_error_count", bucket.errors as f64)
+                    .kind(DataPointType::Measurement)
+                    .build();
+
+                let mut properties = Properties::default();
+                properties.insert("request.name".into(), key.name.clone());
+                properties.insert("request.responseCode".into(), key.response_code.clone());
+                properties.insert(
+                    "request.avgDuration".into(),
+                    RequestTelemetry::format_duration(Duration::from_secs_f64(
+                        bucket.sum / bucket.count as f64 / 1_000.0,
+                    )),
+                );
+
+                [count_metric, duration_metric, error_metric].into_iter().map(move |metric| {
+                    let data = Data::MetricData(
+                        MetricDataBuilder::new(vec![metric])
+                            .properties(properties.clone())
+                            .build(),
+                    );
+
+                    let envelope_name = data.envelope_name(&context.normalized_i_key);
+
+                    EnvelopeBuilder::new(envelope_name, timestamp.clone())
+                        .data(Base::Data(data))
+                        .i_key(context.i_key.clone())
+                        .tags(context.tags.clone())
+                        .build()
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct BucketKey {
+    name: String,
+    response_code: String,
+}
+
+// Running Rate/Error/Duration statistics for a single `(name, response_code)` bucket, kept as
+// sums so a bucket never has to retain raw duration samples.
+#[derive(Default)]
+struct Bucket {
+    count: u64,
+    errors: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    sum_of_squares: f64,
+}
+
+impl Bucket {
+    fn record(&mut self, duration: Duration, is_error: bool) {
+        let millis = duration.as_secs_f64() * 1_000.0;
+
+        if self.count == 0 {
+            self.min = millis;
+            self.max = millis;
+        } else {
+            self.min = self.min.min(millis);
+            self.max = self.max.max(millis);
+        }
+
+        self.count += 1;
+        self.sum += millis;
+        self.sum_of_squares += millis * millis;
+        if is_error {
+            self.errors += 1;
+        }
+    }
+
+    fn std_dev(&self) -> f64 {
+        let count = self.count as f64;
+        let mean = self.sum / count;
+        ((self.sum_of_squares / count) - mean * mean).max(0.0).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn it_aggregates_count_min_max_and_errors() {
+        let mut bucket = Bucket::default();
+        bucket.record(Duration::from_millis(100), false);
+        bucket.record(Duration::from_millis(300), true);
+        bucket.record(Duration::from_millis(200), false);
+
+        assert_eq!(bucket.count, 3);
+        assert_eq!(bucket.errors, 1);
+        assert_eq!(bucket.min, 100.0);
+        assert_eq!(bucket.max, 300.0);
+        assert_eq!(bucket.sum, 600.0);
+    }
+
+    #[test]
+    fn it_reports_the_sum_not_the_mean_as_the_aggregation_value() {
+        time::set(Utc.ymd(2019, 1, 2).and_hms_milli(3, 4, 5, 800));
+        let mut aggregator = RequestMetricsAggregator::new();
+        let context = TelemetryContext::new("instrumentation".into());
+
+        for _ in 0..2 {
+            aggregator.track(
+                &RequestTelemetry::new(
+                    http::Method::GET,
+                    "https://example.com/main.html".parse().unwrap(),
+                    Duration::from_millis(100),
+                    http::StatusCode::OK,
+                ),
+                &context,
+            );
+        }
+
+        let key = BucketKey {
+            name: "GET https://example.com/main.html".to_string(),
+            response_code: "200".to_string(),
+        };
+        assert_eq!(aggregator.buckets[&key].sum, 200.0);
+
+        let mut properties = Properties::default();
+        properties.insert("request.name".into(), key.name.clone());
+        properties.insert("request.responseCode".into(), key.response_code.clone());
+        properties.insert("request.avgDuration".into(), "0.00:00:00.1000000".into());
+        let data = Data::MetricData(
+            MetricDataBuilder::new(vec![DataPointBuilder::new("request_duration", 200.0)
+                .kind(DataPointType::Aggregation)
+                .count(2)
+                .min(100.0)
+                .max(100.0)
+                .std_dev(0.0)
+                .build()])
+            .properties(properties)
+            .build(),
+        );
+        let expected = EnvelopeBuilder::new(data.envelope_name(&context.normalized_i_key), "2019-01-02T03:04:05.800Z")
+            .data(Base::Data(data))
+            .i_key(context.i_key.clone())
+            .tags(context.tags.clone())
+            .build();
+
+        let envelopes = aggregator.collect(&context);
+        assert_eq!(envelopes.len(), 3);
+        assert_eq!(envelopes[1], expected);
+    }
+
+    #[test]
+    fn it_computes_a_zero_std_dev_for_identical_samples() {
+        let mut bucket = Bucket::default();
+        bucket.record(Duration::from_millis(150), false);
+        bucket.record(Duration::from_millis(150), false);
+
+        assert_eq!(bucket.std_dev(), 0.0);
+    }
+
+    #[test]
+    fn it_resets_buckets_after_collecting() {
+        let mut aggregator = RequestMetricsAggregator::new();
+        let context = TelemetryContext::new("instrumentation".into());
+
+        aggregator.track(
+            &RequestTelemetry::new(
+                http::Method::GET,
+                "https://example.com/main.html".parse().unwrap(),
+                Duration::from_millis(100),
+                http::StatusCode::OK,
+            ),
+            &context,
+        );
+
+        assert_eq!(aggregator.collect(&context).len(), 3);
+        assert!(aggregator.collect(&context).is_empty());
+    }
+
+    #[test]
+    fn it_honors_the_context_classifier_when_counting_errors() {
+        let mut aggregator = RequestMetricsAggregator::new();
+        let mut context = TelemetryContext::new("instrumentation".into());
+        context.success_classifier = Some(std::sync::Arc::new(|status| status == http::StatusCode::NOT_FOUND));
+
+        aggregator.track(
+            &RequestTelemetry::new(
+                http::Method::GET,
+                "https://example.com/main.html".parse().unwrap(),
+                Duration::from_millis(100),
+                http::StatusCode::NOT_FOUND,
+            ),
+            &context,
+        );
+
+        let key = BucketKey {
+            name: "GET https://example.com/main.html".to_string(),
+            response_code: "404".to_string(),
+        };
+        assert_eq!(aggregator.buckets[&key].errors, 0);
+    }
+}
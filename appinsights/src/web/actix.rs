@@ -0,0 +1,189 @@
+//! Actix-web middleware that tracks every request as a [`RequestTelemetry`] item, so services
+//! don't have to build and submit one by hand in every handler.
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{Method, StatusCode, Uri};
+use actix_web::{web, Error};
+use futures_util::future::LocalBoxFuture;
+
+use crate::telemetry::{RequestTelemetry, SuccessClassifier};
+use crate::TelemetryClient;
+
+/// Request headers that are useful enough to copy onto every `RequestTelemetry` item as
+/// properties.
+const FORWARDED_HEADERS: [&str; 3] = ["user-agent", "referer", "x-forwarded-for"];
+
+/// Wraps an Actix-web `Service` the way `actix_web::middleware::Logger` does, and emits a
+/// [`RequestTelemetry`] for every request through the `TelemetryClient` registered as app data.
+///
+/// ```no_run
+/// use actix_web::{web, App};
+/// use appinsights::web::actix::TelemetryMiddleware;
+/// use appinsights::TelemetryClient;
+///
+/// let client = TelemetryClient::new("<instrumentation key>".to_string());
+///
+/// App::new()
+///     .app_data(web::Data::new(client))
+///     .wrap(TelemetryMiddleware::new());
+/// ```
+#[derive(Default)]
+pub struct TelemetryMiddleware {
+    classifier: Option<SuccessClassifier>,
+}
+
+impl TelemetryMiddleware {
+    /// Creates a middleware that uses `RequestTelemetry`'s default success classification.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classifies every tracked request's success with `classify` instead of the
+    /// `TelemetryContext`-wide classifier (or the default rule, anything below 400 plus 401, if
+    /// none is configured there), via [`RequestTelemetry::set_shared_success_classifier`].
+    pub fn with_success_classifier(mut self, classify: impl Fn(StatusCode) -> bool + Send + Sync + 'static) -> Self {
+        self.classifier = Some(Arc::new(classify));
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for TelemetryMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = TelemetryMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TelemetryMiddlewareService {
+            service: Rc::new(service),
+            classifier: self.classifier.clone(),
+        }))
+    }
+}
+
+pub struct TelemetryMiddlewareService<S> {
+    service: Rc<S>,
+    classifier: Option<SuccessClassifier>,
+}
+
+impl<S, B> Service<ServiceRequest> for TelemetryMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let client = req.app_data::<web::Data<TelemetryClient>>().cloned();
+        let method = req.method().clone();
+        let uri = full_uri(&req);
+        // Routing happens inside the inner service, so `req.match_pattern()` isn't populated yet
+        // here. Keep the raw path as a fallback name for the cases (errors) where we never get a
+        // `ServiceResponse` to read the matched pattern back off of.
+        let fallback_name = format!("{} {}", method, req.path());
+        // `req` is moved into `service.call(req)` below and isn't available in the error arm, so
+        // the headers have to be copied out up front to be captured in both arms.
+        let headers = forwarded_headers(&req);
+        let started = Instant::now();
+        let service = self.service.clone();
+        let classifier = self.classifier.clone();
+
+        Box::pin(async move {
+            // Run the handler first so the telemetry item also covers requests that complete
+            // with an error response body, then track it regardless of outcome.
+            match service.call(req).await {
+                Ok(res) => {
+                    if let Some(client) = &client {
+                        let name = route_name(&res, &method, &fallback_name);
+                        let mut telemetry = RequestTelemetry::new(method, uri, started.elapsed(), res.status());
+                        *telemetry.name_mut() = name;
+
+                        track(client, telemetry, classifier, headers);
+                    }
+
+                    Ok(res)
+                }
+                Err(err) => {
+                    if let Some(client) = &client {
+                        let status = err.as_response_error().status_code();
+                        let mut telemetry = RequestTelemetry::new(method, uri, started.elapsed(), status);
+                        *telemetry.name_mut() = fallback_name;
+
+                        track(client, telemetry, classifier, headers);
+                    }
+
+                    Err(err)
+                }
+            }
+        })
+    }
+}
+
+/// Copies `FORWARDED_HEADERS` off a request before it's moved into the inner service.
+fn forwarded_headers(req: &ServiceRequest) -> Vec<(&'static str, String)> {
+    FORWARDED_HEADERS
+        .iter()
+        .filter_map(|header| {
+            req.headers()
+                .get(*header)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| (*header, value.to_string()))
+        })
+        .collect()
+}
+
+/// Applies the captured headers and the middleware's shared classifier, if any, before
+/// submitting the telemetry item.
+fn track(
+    client: &TelemetryClient,
+    mut telemetry: RequestTelemetry,
+    classifier: Option<SuccessClassifier>,
+    headers: Vec<(&'static str, String)>,
+) {
+    for (header, value) in headers {
+        telemetry.properties_mut().insert(header.into(), value);
+    }
+
+    if let Some(classifier) = classifier {
+        telemetry.set_shared_success_classifier(classifier);
+    }
+
+    client.track(telemetry);
+}
+
+/// Reconstructs the full `Uri` (scheme, authority, path and query) for a request, since
+/// `ServiceRequest::uri` only carries the path and query actix-web parsed off the request line.
+fn full_uri(req: &ServiceRequest) -> Uri {
+    let conn = req.connection_info();
+    format!("{}://{}{}", conn.scheme(), conn.host(), req.uri())
+        .parse()
+        .unwrap_or_else(|_| req.uri().clone())
+}
+
+/// Uses the matched resource pattern (e.g. `/users/{id}`) instead of the raw path, so that
+/// `RequestTelemetry::name` aggregates correctly across requests to the same route. The pattern
+/// is only known once the inner service has routed the request, so this reads it off the
+/// `ServiceResponse` rather than the pre-call `ServiceRequest`.
+fn route_name<B>(res: &ServiceResponse<B>, method: &Method, fallback: &str) -> String {
+    match res.request().match_pattern() {
+        Some(pattern) => format!("{} {}", method, pattern),
+        None => fallback.to_string(),
+    }
+}
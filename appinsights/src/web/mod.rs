@@ -0,0 +1,4 @@
+//! Integrations with third-party web frameworks.
+
+#[cfg(feature = "actix")]
+pub mod actix;